@@ -7,6 +7,7 @@
 // except according to those terms.
 
 extern crate bzip2;
+extern crate cc;
 extern crate libc;
 extern crate pkg_config;
 extern crate tar;
@@ -18,175 +19,396 @@ use std::ffi::{OsString};
 use std::fs;
 use std::fs::{OpenOptions};
 use std::io;
-use std::io::{ErrorKind};
+use std::io::{ErrorKind, Write};
 use std::iter::{FromIterator};
 use std::path::{Path, PathBuf};
-use std::process::{Command};
 use std::vec::{Vec};
 use tar::{Archive};
 
 const BUNDLED_PCRE_VERSION: &'static str = "8.37";
 
+/// The core 8-bit PCRE translation units, relative to the extracted source
+/// directory. `pcre_chartables.c` is generated separately from the bundled
+/// `pcre_chartables.c.dist` rather than compiled from this list.
+const PCRE8_SOURCES: &'static [&'static str] = &[
+    "pcre_byte_order.c",
+    "pcre_compile.c",
+    "pcre_config.c",
+    "pcre_dfa_exec.c",
+    "pcre_exec.c",
+    "pcre_fullinfo.c",
+    "pcre_get.c",
+    "pcre_globals.c",
+    "pcre_jit_compile.c",
+    "pcre_maketables.c",
+    "pcre_newline.c",
+    "pcre_ord2utf8.c",
+    "pcre_refcount.c",
+    "pcre_string_utils.c",
+    "pcre_study.c",
+    "pcre_tables.c",
+    "pcre_ucd.c",
+    "pcre_valid_utf8.c",
+    "pcre_version.c",
+    "pcre_xclass.c",
+];
+
+/// The 16-bit translation units, compiled into their own static library
+/// when the `pcre16` feature is enabled. `pcre16_chartables.c` is generated
+/// separately rather than compiled from this list.
+const PCRE16_SOURCES: &'static [&'static str] = &[
+    "pcre16_byte_order.c",
+    "pcre16_compile.c",
+    "pcre16_config.c",
+    "pcre16_dfa_exec.c",
+    "pcre16_exec.c",
+    "pcre16_fullinfo.c",
+    "pcre16_get.c",
+    "pcre16_globals.c",
+    "pcre16_maketables.c",
+    "pcre16_newline.c",
+    "pcre16_ord2utf16.c",
+    "pcre16_refcount.c",
+    "pcre16_string_utils.c",
+    "pcre16_study.c",
+    "pcre16_tables.c",
+    "pcre16_ucd.c",
+    "pcre16_utf16_utils.c",
+    "pcre16_valid_utf16.c",
+    "pcre16_version.c",
+    "pcre16_xclass.c",
+];
+
+/// The 32-bit translation units, compiled into their own static library
+/// when the `pcre32` feature is enabled. `pcre32_chartables.c` is generated
+/// separately rather than compiled from this list.
+const PCRE32_SOURCES: &'static [&'static str] = &[
+    "pcre32_byte_order.c",
+    "pcre32_compile.c",
+    "pcre32_config.c",
+    "pcre32_dfa_exec.c",
+    "pcre32_exec.c",
+    "pcre32_fullinfo.c",
+    "pcre32_get.c",
+    "pcre32_globals.c",
+    "pcre32_maketables.c",
+    "pcre32_newline.c",
+    "pcre32_ord2utf32.c",
+    "pcre32_refcount.c",
+    "pcre32_string_utils.c",
+    "pcre32_study.c",
+    "pcre32_tables.c",
+    "pcre32_ucd.c",
+    "pcre32_utf32_utils.c",
+    "pcre32_valid_utf32.c",
+    "pcre32_version.c",
+    "pcre32_xclass.c",
+];
+
+/// Returns whether the given Cargo feature (e.g. `"pcre16"`) was enabled for
+/// this build, by checking the `CARGO_FEATURE_<NAME>` environment variable
+/// Cargo sets for every feature in the manifest.
+fn feature_enabled(name: &str) -> bool {
+    env::var_os(format!("CARGO_FEATURE_{}", name.to_uppercase())).is_some()
+}
+
+/// Which step of the bundled build failed, spliced into panic messages so a
+/// broken build points at a concrete step and directory instead of a bare
+/// "operation failed".
+#[derive(Copy, Clone)]
+enum Phase {
+    Extract,
+    GenerateHeaders,
+    CcBuild,
+}
+
+impl Phase {
+    fn name(&self) -> &'static str {
+        match *self {
+            Phase::Extract => "extract",
+            Phase::GenerateHeaders => "generate-headers",
+            Phase::CcBuild => "cc-build",
+        }
+    }
+}
+
+/// Unwraps a `Result`, panicking with the failing phase and working
+/// directory folded into the message instead of the bare `Debug` output
+/// `.unwrap()` would produce.
+macro_rules! t {
+    ($phase:expr, $dir:expr, $e:expr) => {
+        match $e {
+            Ok(v) => v,
+            Err(e) => panic!("[{}] in {}: {}", $phase.name(), $dir.display(), e),
+        }
+    };
+}
+
+/// Writes a thin wrapper that `#define`s `compile_define` and `#include`s
+/// the already-generated `pcre_chartables.c`, so the shared `PRIV()` macro
+/// renames `default_tables` to the width-correct symbol (e.g.
+/// `_pcre16_default_tables`) instead of producing a raw duplicate of the
+/// 8-bit table under the wrong name.
+fn write_width_chartables_stub(dir: &Path, compile_define: &str, stub_filename: &str) {
+    let stub_pathbuf = dir.join(stub_filename);
+    let contents = format!("#define {}\n\n#include \"pcre_chartables.c\"\n", compile_define);
+    let mut stub_f = t!(Phase::GenerateHeaders, dir, OpenOptions::new().write(true).create(true).truncate(true).open(&stub_pathbuf));
+    t!(Phase::GenerateHeaders, dir, stub_f.write_all(contents.as_bytes()));
+}
+
+/// The minimum system `libpcre` version accepted by the `system` and
+/// `dynamic` link modes, overridable via `LIBPCRE_SYS_MIN_VERSION` for
+/// consumers that need a newer guarantee than the crate's default.
+fn minimum_version() -> String {
+    println!("cargo:rerun-if-env-changed=LIBPCRE_SYS_MIN_VERSION");
+    env::var("LIBPCRE_SYS_MIN_VERSION").unwrap_or_else(|_| String::from("8.20"))
+}
+
+/// Maps a `--enable-newline-is-*` style name to the numeric `NEWLINE` value
+/// PCRE's internals switch on, matching the values `configure` assigns.
+fn newline_value(name: &str) -> i32 {
+    match name {
+        "lf" => 10,
+        "cr" => 13,
+        "crlf" => 3338,
+        "anycrlf" => -2,
+        "any" => -1,
+        other => panic!("unrecognized LIBPCRE_SYS_NEWLINE value `{}` (expected one of: lf, cr, crlf, anycrlf, any)", other),
+    }
+}
+
+/// How `libpcre` gets linked into the final binary. Selected by the
+/// mutually exclusive `system`/`dynamic` Cargo features; building the
+/// bundled static library is the default when neither is set.
+enum LinkMode {
+    /// Require pkg-config to find a system `libpcre`; hard error otherwise.
+    System,
+    /// Require pkg-config to find a *shared* system `libpcre`.
+    Dynamic,
+    /// Build PCRE from the bundled sources as a static library.
+    Bundled,
+}
+
+fn link_mode() -> LinkMode {
+    if feature_enabled("system") {
+        LinkMode::System
+    } else if feature_enabled("dynamic") {
+        LinkMode::Dynamic
+    } else {
+        LinkMode::Bundled
+    }
+}
+
 fn main() {
-    match pkg_config::Config::new().atleast_version("8.20").find("libpcre") {
-        Ok(pkg_config_lib) => {
+    let want_pcre16 = feature_enabled("pcre16");
+    let want_pcre32 = feature_enabled("pcre32");
+
+    // Cross-compilation used to need a Rust-target-triple-to-GNU-triple
+    // translation table to pass `--host` to `./configure`/CMake. Now that
+    // the bundled build goes through `cc::Build` (see `build_bundled`), it
+    // reads Cargo's `TARGET` (and `CC_<target>`/`AR_<target>`/`CFLAGS`)
+    // itself, so there is nothing left for this build script to translate
+    // or forward.
+    let minimum_version = minimum_version();
+
+    match link_mode() {
+        LinkMode::System => {
+            let pkg_config_lib = pkg_config::Config::new().atleast_version(&minimum_version).find("libpcre")
+                .unwrap_or_else(|e| panic!("the `system` feature requires pkg-config to find libpcre >= {}: {}", minimum_version, e));
             for link_pathbuf in pkg_config_lib.link_paths {
                 println!("cargo:rustc-link-search=native={}", link_pathbuf.as_path().display());
             }
         },
-        Err(_) => {
-            let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
-            let out_dir = env::var("OUT_DIR").unwrap();
-
-            let ext_pathbuf = Path::new(&cargo_manifest_dir).join("ext");
-
-            let pcre_tbz2_pathbuf = ext_pathbuf.join(format!("pcre-{}.tar.bz2", BUNDLED_PCRE_VERSION));
-            let pcre_tbz2_f = OpenOptions::new().read(true).open(pcre_tbz2_pathbuf).unwrap();
-            let decompressor = BzDecompressor::new(pcre_tbz2_f);
-
-            let mut archive = Archive::new(decompressor);
-            // Keep track of which directory paths have already been created.
-            let mut created_paths: BTreeSet<OsString> = BTreeSet::new();
-            for file in archive.files_mut().unwrap() {
-                let mut file = file.unwrap();
-                let filename = String::from(file.filename().unwrap());
-
-                let mut path_parts: Vec<&str> = filename.split('/').collect();
-                let filename = path_parts.pop().unwrap();
-                let parent_pathbuf = Path::new(&out_dir).join(PathBuf::from_iter(path_parts.iter()));
-                if !path_parts.is_empty() {
-                    if created_paths.insert(parent_pathbuf.as_os_str().to_os_string()) {
-                        if let Err(e) = fs::create_dir_all(&parent_pathbuf) {
-                            if e.kind() != ErrorKind::AlreadyExists {
-                                panic!("failed to create the {} directory and parents: {}", parent_pathbuf.as_path().display(), e);
-                            }
-                        }
-                    }
-                }
+        LinkMode::Dynamic => {
+            let pkg_config_lib = pkg_config::Config::new().atleast_version(&minimum_version).statik(false).cargo_metadata(false).find("libpcre")
+                .unwrap_or_else(|e| panic!("the `dynamic` feature requires pkg-config to find a shared libpcre >= {}: {}", minimum_version, e));
+            for link_pathbuf in pkg_config_lib.link_paths {
+                println!("cargo:rustc-link-search=native={}", link_pathbuf.as_path().display());
+            }
+            println!("cargo:rustc-link-lib=dylib=pcre");
+        },
+        LinkMode::Bundled => {
+            build_bundled(want_pcre16, want_pcre32);
+        },
+    };
+}
 
-                let out_pathbuf = parent_pathbuf.join(&filename);
-                if filename.is_empty() {
-                    if created_paths.insert(out_pathbuf.as_os_str().to_os_string()) {
-                        if let Err(e) = fs::create_dir(&out_pathbuf) {
-                            if e.kind() != ErrorKind::AlreadyExists {
-                                panic!("failed to create the {} directory: {}", out_pathbuf.as_path().display(), e);
-                            }
-                        }
-                    }
-                } else {
-                    let mut f = OpenOptions::new().write(true).create(true).open(&out_pathbuf).unwrap();
-                    if let Err(e) = io::copy(&mut file, &mut f) {
-                        panic!("failed to extract {} to {}: {}", filename, out_pathbuf.as_path().display(), e);
+/// Builds the bundled PCRE sources into a static library and links it in.
+fn build_bundled(want_pcre16: bool, want_pcre32: bool) {
+    let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = env::var("OUT_DIR").unwrap();
+
+    let ext_pathbuf = Path::new(&cargo_manifest_dir).join("ext");
+
+    eprintln!("[{}] extracting bundled PCRE {} sources into {}", Phase::Extract.name(), BUNDLED_PCRE_VERSION, out_dir);
+
+    let pcre_tbz2_pathbuf = ext_pathbuf.join(format!("pcre-{}.tar.bz2", BUNDLED_PCRE_VERSION));
+    let pcre_tbz2_f = t!(Phase::Extract, ext_pathbuf, OpenOptions::new().read(true).open(&pcre_tbz2_pathbuf));
+    let decompressor = BzDecompressor::new(pcre_tbz2_f);
+
+    let mut archive = Archive::new(decompressor);
+    // Keep track of which directory paths have already been created.
+    let mut created_paths: BTreeSet<OsString> = BTreeSet::new();
+    for file in archive.files_mut().unwrap() {
+        let mut file = file.unwrap();
+        let filename = String::from(file.filename().unwrap());
+
+        let mut path_parts: Vec<&str> = filename.split('/').collect();
+        let filename = path_parts.pop().unwrap();
+        let parent_pathbuf = Path::new(&out_dir).join(PathBuf::from_iter(path_parts.iter()));
+        if !path_parts.is_empty() {
+            if created_paths.insert(parent_pathbuf.as_os_str().to_os_string()) {
+                if let Err(e) = fs::create_dir_all(&parent_pathbuf) {
+                    if e.kind() != ErrorKind::AlreadyExists {
+                        panic!("failed to create the {} directory and parents: {}", parent_pathbuf.as_path().display(), e);
                     }
                 }
-                if cfg!(unix) {
-                    // TODO Use `std::fs::Permissions` when `std::os::unix::fs::PermissionsExt` is stable.
-                    use std::ffi::{CString};
-                    use std::os::unix::ffi::{OsStringExt};
-                    let out_pathbuf_cstring = CString::new(out_pathbuf.as_os_str().to_os_string().into_vec()).unwrap();
-                    let mode = file.mode().unwrap();
-                    unsafe {
-                        libc::chmod(out_pathbuf_cstring.as_ptr(), mode as libc::mode_t);
+            }
+        }
+
+        let out_pathbuf = parent_pathbuf.join(&filename);
+        if filename.is_empty() {
+            if created_paths.insert(out_pathbuf.as_os_str().to_os_string()) {
+                if let Err(e) = fs::create_dir(&out_pathbuf) {
+                    if e.kind() != ErrorKind::AlreadyExists {
+                        panic!("failed to create the {} directory: {}", out_pathbuf.as_path().display(), e);
                     }
                 }
             }
+        } else {
+            let mut f = t!(Phase::Extract, out_pathbuf, OpenOptions::new().write(true).create(true).open(&out_pathbuf));
+            t!(Phase::Extract, out_pathbuf, io::copy(&mut file, &mut f));
+        }
+        if cfg!(unix) {
+            // TODO Use `std::fs::Permissions` when `std::os::unix::fs::PermissionsExt` is stable.
+            use std::ffi::{CString};
+            use std::os::unix::ffi::{OsStringExt};
+            let out_pathbuf_cstring = CString::new(out_pathbuf.as_os_str().to_os_string().into_vec()).unwrap();
+            let mode = file.mode().unwrap();
+            unsafe {
+                libc::chmod(out_pathbuf_cstring.as_ptr(), mode as libc::mode_t);
+            }
+        }
+    }
 
-            let pcre_pathbuf = Path::new(&out_dir).join(format!("pcre-{}", BUNDLED_PCRE_VERSION));
-
-            if cfg!(unix) {
-                let mut cmd = Command::new("autoreconf");
-                cmd.current_dir(&pcre_pathbuf);
-                let status = match cmd.status() {
-                    Err(ref e) if e.kind() == ErrorKind::NotFound => {
-                        panic!("failed to execute `autoreconf`: {}. Are the Autotools installed?", e);
-                    },
-                    Err(e) => {
-                        panic!("failed to execute `autoreconf`: {}", e);
-                    },
-                    Ok(status) => status
-                };
-                if !status.success() {
-                    panic!("`autoreconf` did not run successfully.");
-                }
+    let pcre_pathbuf = Path::new(&out_dir).join(format!("pcre-{}", BUNDLED_PCRE_VERSION));
 
-                let mut cmd = Command::new("./configure");
-                cmd.arg("--with-pic");
-                cmd.arg("--disable-shared");
-                cmd.arg("--disable-cpp");
-                cmd.arg("--enable-jit");
-                cmd.arg("--enable-utf");
-                cmd.arg("--enable-unicode-properties");
-                cmd.arg(format!("--prefix={}", Path::new(&out_dir).display()));
-                cmd.current_dir(&pcre_pathbuf);
-                let status = match cmd.status() {
-                    Err(e) => {
-                        panic!("failed to execute `./configure`: {}", e);
-                    },
-                    Ok(status) => status
-                };
-                if !status.success() {
-                    panic!("`./configure --with-pic ...` did not run successfully.");
-                }
+    // `config.h.generic` and `pcre.h.generic` are the distribution's own
+    // no-autotools drop-ins (the same role `pcre_chartables.c.dist` plays
+    // below) with every `HAVE_*`/`PCRE_MAJOR`-style value already baked in,
+    // so a plain copy stands in for `./configure`'s `config.status` run.
+    eprintln!("[{}] generating config.h and pcre.h in {}", Phase::GenerateHeaders.name(), pcre_pathbuf.display());
+    t!(Phase::GenerateHeaders, pcre_pathbuf, fs::copy(pcre_pathbuf.join("config.h.generic"), pcre_pathbuf.join("config.h")));
+    t!(Phase::GenerateHeaders, pcre_pathbuf, fs::copy(pcre_pathbuf.join("pcre.h.generic"), pcre_pathbuf.join("pcre.h")));
+    // The default C-locale character tables are shipped pre-built;
+    // there is no need to build and run `dftables` for them.
+    t!(Phase::GenerateHeaders, pcre_pathbuf, fs::copy(pcre_pathbuf.join("pcre_chartables.c.dist"), pcre_pathbuf.join("pcre_chartables.c")));
+    if want_pcre16 {
+        write_width_chartables_stub(&pcre_pathbuf, "COMPILE_PCRE16", "pcre16_chartables.c");
+    }
+    if want_pcre32 {
+        write_width_chartables_stub(&pcre_pathbuf, "COMPILE_PCRE32", "pcre32_chartables.c");
+    }
 
-                let mut cmd = Command::new("make");
-                cmd.arg("install");
-                cmd.current_dir(&pcre_pathbuf);
-                let status = match cmd.status() {
-                    Err(ref e) if e.kind() == ErrorKind::NotFound => {
-                        panic!("failed to execute `make`: {}. Is GNU Make installed?", e);
-                    },
-                    Err(e) => {
-                        panic!("failed to execute `make`: {}", e);
-                    },
-                    Ok(status) => status
-                };
-                if !status.success() {
-                    panic!("`make install` did not run successfully.");
-                }
+    println!("cargo:rerun-if-env-changed=LIBPCRE_SYS_LINK_SIZE");
+    println!("cargo:rerun-if-env-changed=LIBPCRE_SYS_MATCH_LIMIT");
+    println!("cargo:rerun-if-env-changed=LIBPCRE_SYS_MATCH_LIMIT_RECURSION");
+    println!("cargo:rerun-if-env-changed=LIBPCRE_SYS_NEWLINE");
+    let link_size = env::var("LIBPCRE_SYS_LINK_SIZE").unwrap_or_else(|_| String::from("2"));
+    let match_limit = env::var("LIBPCRE_SYS_MATCH_LIMIT").ok();
+    let match_limit_recursion = env::var("LIBPCRE_SYS_MATCH_LIMIT_RECURSION").ok();
+    let newline = env::var("LIBPCRE_SYS_NEWLINE").unwrap_or_else(|_| String::from("lf"));
+    let newline = newline_value(&newline);
 
-                println!("cargo:rustc-link-search=native={}", Path::new(&out_dir).join("lib").as_path().display());
-            } else {
-                let mut cmd = Command::new("cmake");
-                cmd.arg(".");
-                cmd.arg("-DBUILD_SHARED_LIBS=OFF");
-                cmd.arg("-DPCRE_BUILD_PCRECPP=OFF");
-                cmd.arg("-DPCRE_BUILD_PCREGREP=OFF");
-                cmd.arg("-DPCRE_BUILD_TESTS=OFF");
-                cmd.arg("-DPCRE_BUILD_PCRE8=ON");
-                cmd.arg("-DPCRE_SUPPORT_JIT=ON");
-                cmd.arg("-DPCRE_SUPPORT_UTF=ON");
-                cmd.arg("-DPCRE_SUPPORT_UNICODE_PROPERTIES=ON");
-                cmd.current_dir(&pcre_pathbuf);
-                let status = match cmd.status() {
-                    Err(ref e) if e.kind() == ErrorKind::NotFound => {
-                        panic!("failed to execute `cmake`: {}. Is CMake installed?", e);
-                    },
-                    Err(e) => {
-                        panic!("failed to execute `cmake`: {}", e);
-                    },
-                    Ok(status) => status
-                };
-                if !status.success() {
-                    panic!("`cmake . -DBUILD_SHARED_LIBS=OFF ...` did not run successfully.");
-                }
+    // Each width is scoped to its own translation units via `COMPILE_PCREn`
+    // (which is what makes `PRIV()` emit the width-correct symbol names),
+    // so every width needs its own `cc::Build` rather than one shared build
+    // with just an advertising `SUPPORT_PCREn` define.
+    // Only PCRE8_SOURCES includes a JIT translation unit (pcre_jit_compile.c);
+    // the 16/32-bit source lists have none, so defining SUPPORT_JIT for them
+    // would leave pcre16_study.c/pcre16_exec.c's PRIV(jit_compile) references
+    // unresolved.
+    compile_width(&pcre_pathbuf, "COMPILE_PCRE8", "pcre", "pcre_chartables.c", PCRE8_SOURCES,
+                  &link_size, &match_limit, &match_limit_recursion, newline, want_pcre16, want_pcre32, true);
+    if want_pcre16 {
+        compile_width(&pcre_pathbuf, "COMPILE_PCRE16", "pcre16", "pcre16_chartables.c", PCRE16_SOURCES,
+                      &link_size, &match_limit, &match_limit_recursion, newline, want_pcre16, want_pcre32, false);
+    }
+    if want_pcre32 {
+        compile_width(&pcre_pathbuf, "COMPILE_PCRE32", "pcre32", "pcre32_chartables.c", PCRE32_SOURCES,
+                      &link_size, &match_limit, &match_limit_recursion, newline, want_pcre16, want_pcre32, false);
+    }
 
-                let mut cmd = Command::new("cmake");
-                cmd.arg("--build").arg(".").current_dir(&pcre_pathbuf);
-                let status = match cmd.status() {
-                    Err(ref e) if e.kind() == ErrorKind::NotFound => {
-                        panic!("failed to execute `cmake`: {}. Is CMake installed?", e);
-                    },
-                    Err(e) => {
-                        panic!("failed to execute `cmake`: {}", e);
-                    },
-                    Ok(status) => status
-                };
-                if !status.success() {
-                    panic!("`cmake --build .` did not run successfully.");
-                }
+    if want_pcre16 {
+        println!("cargo:rustc-cfg=pcre16");
+    }
+    if want_pcre32 {
+        println!("cargo:rustc-cfg=pcre32");
+    }
+}
 
-                println!("cargo:rustc-link-search=native={}", pcre_pathbuf.as_path().display());
-            }
-        }
-    };
+/// Compiles one PCRE character width into its own static library named
+/// `lib_name` (e.g. `"pcre16"`), scoped to that width via `compile_define`
+/// (`COMPILE_PCRE8`/`16`/`32`), which is what makes `PRIV()` in the shared
+/// sources emit the width-correct `_pcre_`/`_pcre16_`/`_pcre32_` symbols.
+fn compile_width(
+    pcre_pathbuf: &Path,
+    compile_define: &str,
+    lib_name: &str,
+    chartables_file: &str,
+    sources: &[&str],
+    link_size: &str,
+    match_limit: &Option<String>,
+    match_limit_recursion: &Option<String>,
+    newline: i32,
+    want_pcre16: bool,
+    want_pcre32: bool,
+    support_jit: bool,
+) {
+    let mut build = cc::Build::new();
+    build.include(pcre_pathbuf);
+    build.define("HAVE_CONFIG_H", None);
+    build.define("PCRE_STATIC", None);
+    build.define("SUPPORT_UTF", None);
+    build.define("SUPPORT_UCP", None);
+    if support_jit {
+        build.define("SUPPORT_JIT", None);
+    }
+    build.define(compile_define, None);
+    // Advertised to every width's translation units (not just the ones
+    // being built), since PCRE's headers use these to declare the other
+    // widths' interop entry points alongside this width's own.
+    if want_pcre16 {
+        build.define("SUPPORT_PCRE16", None);
+    }
+    if want_pcre32 {
+        build.define("SUPPORT_PCRE32", None);
+    }
+
+    build.define("LINK_SIZE", Some(link_size));
+    if let Some(ref match_limit) = *match_limit {
+        build.define("MATCH_LIMIT", Some(match_limit.as_str()));
+    }
+    if let Some(ref match_limit_recursion) = *match_limit_recursion {
+        build.define("MATCH_LIMIT_RECURSION", Some(match_limit_recursion.as_str()));
+    }
+    let newline = newline.to_string();
+    build.define("NEWLINE", Some(newline.as_str()));
+
+    // `cc::Build` already reads `CC_<target>`/`AR_<target>`/`CFLAGS`/
+    // `CFLAGS_<target>` from the environment on its own; adding our own
+    // lookups here would at best duplicate that and at worst double-apply
+    // `CFLAGS`.
+    build.file(pcre_pathbuf.join(chartables_file));
+    for source in sources {
+        build.file(pcre_pathbuf.join(source));
+    }
+
+    eprintln!("[{}] compiling bundled PCRE {} ({}) with cc::Build in {}",
+              Phase::CcBuild.name(), BUNDLED_PCRE_VERSION, compile_define, pcre_pathbuf.display());
+    // `compile` panics internally with `cc`'s own (untagged) message on
+    // failure; `try_compile` surfaces the same failure as a `Result` so it
+    // can be folded into the phase/directory-tagged panic like every other
+    // fallible step here.
+    t!(Phase::CcBuild, pcre_pathbuf, build.try_compile(lib_name));
 }